@@ -0,0 +1,124 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::io::{Read, Write};
+
+use crate::transaction::{double_sha256, CompactSize, Decodable, Encodable, Error, Transaction};
+
+/// An 80-byte block header, per the Bitcoin wire format.
+#[derive(fmt::Debug)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub prev_blockhash: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    /// Double-SHA256 of the 80-byte header serialization.
+    pub fn block_hash(&self) -> BlockHash {
+        let mut header_data = Vec::new();
+        self.consensus_encode(&mut header_data).unwrap();
+        BlockHash(double_sha256(&header_data))
+    }
+}
+
+impl Encodable for BlockHeader {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
+        let mut len = 0;
+        len += self.version.consensus_encode(writer)?;
+        len += writer.write(&self.prev_blockhash).map_err(Error::Io)?;
+        len += writer.write(&self.merkle_root).map_err(Error::Io)?;
+        len += self.time.consensus_encode(writer)?;
+        len += self.bits.consensus_encode(writer)?;
+        len += self.nonce.consensus_encode(writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for BlockHeader {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let version = u32::consensus_decode_from_finite_reader(reader)?;
+
+        let mut prev_blockhash = [0u8; 32];
+        reader.read_exact(&mut prev_blockhash).map_err(Error::Io)?;
+
+        let mut merkle_root = [0u8; 32];
+        reader.read_exact(&mut merkle_root).map_err(Error::Io)?;
+
+        let time = u32::consensus_decode_from_finite_reader(reader)?;
+        let bits = u32::consensus_decode_from_finite_reader(reader)?;
+        let nonce = u32::consensus_decode_from_finite_reader(reader)?;
+
+        Ok(BlockHeader {
+            version,
+            prev_blockhash,
+            merkle_root,
+            time,
+            bits,
+            nonce,
+        })
+    }
+}
+
+impl Serialize for BlockHeader {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut prev_blockhash = self.prev_blockhash;
+        prev_blockhash.reverse();
+        let mut merkle_root = self.merkle_root;
+        merkle_root.reverse();
+
+        let mut header = serializer.serialize_struct("BlockHeader", 7)?;
+        header.serialize_field("block_hash", &self.block_hash())?;
+        header.serialize_field("version", &self.version)?;
+        header.serialize_field("prev_blockhash", &hex::encode(prev_blockhash))?;
+        header.serialize_field("merkle_root", &hex::encode(merkle_root))?;
+        header.serialize_field("time", &self.time)?;
+        header.serialize_field("bits", &self.bits)?;
+        header.serialize_field("nonce", &self.nonce)?;
+        header.end()
+    }
+}
+
+/// A block hash, displayed reversed (like `Txid`), per Bitcoin convention.
+#[derive(fmt::Debug)]
+pub struct BlockHash([u8; 32]);
+
+impl Serialize for BlockHash {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut bytes = self.0;
+        bytes.reverse();
+        s.serialize_str(&hex::encode(bytes))
+    }
+}
+
+#[derive(fmt::Debug, Serialize)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub txdata: Vec<Transaction>,
+}
+
+impl Encodable for Block {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
+        let mut len = 0;
+        len += self.header.consensus_encode(writer)?;
+        len += CompactSize(self.txdata.len() as u64).consensus_encode(writer)?;
+        for tx in self.txdata.iter() {
+            len += tx.consensus_encode(writer)?;
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for Block {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let header = BlockHeader::consensus_decode_from_finite_reader(reader)?;
+        let txdata = Vec::<Transaction>::consensus_decode_from_finite_reader(reader)?;
+        Ok(Block { header, txdata })
+    }
+}
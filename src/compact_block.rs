@@ -0,0 +1,270 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::io::{Error as IOError, ErrorKind};
+use std::io::{Read, Write};
+
+use crate::block::BlockHeader;
+use crate::transaction::{
+    decode_vec, double_sha256, CompactSize, Decodable, Encodable, Error, Transaction,
+    MAX_VEC_ALLOC_CAPACITY,
+};
+
+/// A BIP152 short transaction ID: the 6 least significant bytes of
+/// SipHash-2-4(wtxid) under a key derived from the block header and nonce.
+pub type ShortId = [u8; 6];
+
+impl Encodable for ShortId {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
+        writer.write(self).map_err(Error::Io)
+    }
+}
+
+impl Decodable for ShortId {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let mut buffer = [0u8; 6];
+        reader.read_exact(&mut buffer).map_err(Error::Io)?;
+        Ok(buffer)
+    }
+}
+
+impl Encodable for Vec<ShortId> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
+        let mut len = 0;
+        len += CompactSize(self.len() as u64).consensus_encode(writer)?;
+        for id in self.iter() {
+            len += id.consensus_encode(writer)?;
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for Vec<ShortId> {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let len = CompactSize::consensus_decode_from_finite_reader(reader)?.0;
+        decode_vec(len, reader)
+    }
+}
+
+/// A transaction the sender includes in full alongside a compact block,
+/// e.g. the coinbase. `index` is the transaction's absolute position in the
+/// block; on the wire it is differentially encoded against the previous
+/// prefilled index (BIP152), which is handled by `Vec<PrefilledTransaction>`'s
+/// `Encodable`/`Decodable` impls rather than by this type on its own.
+#[derive(fmt::Debug, Serialize)]
+pub struct PrefilledTransaction {
+    pub index: u64,
+    pub tx: Transaction,
+}
+
+impl Encodable for Vec<PrefilledTransaction> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
+        let mut len = 0;
+        len += CompactSize(self.len() as u64).consensus_encode(writer)?;
+        let mut last_index: i64 = -1;
+        for prefilled in self.iter() {
+            let diff = prefilled.index as i64 - last_index - 1;
+            len += CompactSize(diff as u64).consensus_encode(writer)?;
+            len += prefilled.tx.consensus_encode(writer)?;
+            last_index = prefilled.index as i64;
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for Vec<PrefilledTransaction> {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let count = CompactSize::consensus_decode_from_finite_reader(reader)?.0;
+        let mut ret =
+            Vec::with_capacity(std::cmp::min(count, MAX_VEC_ALLOC_CAPACITY as u64) as usize);
+        // `None` stands in for the BIP152 "previous index is -1" base case;
+        // every step afterwards is `previous + 1 + diff`, checked so that an
+        // attacker-controlled `diff` can't wrap the index around instead of
+        // being rejected outright.
+        let mut last_index: Option<u64> = None;
+        for _ in 0..count {
+            let diff = CompactSize::consensus_decode_from_finite_reader(reader)?.0;
+            let index = match last_index {
+                None => Some(diff),
+                Some(previous) => previous.checked_add(1).and_then(|v| v.checked_add(diff)),
+            }
+            .ok_or_else(|| {
+                Error::Io(IOError::new(
+                    ErrorKind::InvalidData,
+                    "prefilled transaction index overflow",
+                ))
+            })?;
+            let tx = Transaction::consensus_decode_from_finite_reader(reader)?;
+            ret.push(PrefilledTransaction { index, tx });
+            last_index = Some(index);
+        }
+        Ok(ret)
+    }
+}
+
+/// A BIP152 `cmpctblock` message: a header plus the short IDs and prefilled
+/// transactions needed to reconstruct the full block from a mempool.
+#[derive(fmt::Debug)]
+pub struct CompactBlock {
+    pub header: BlockHeader,
+    pub nonce: u64,
+    pub short_ids: Vec<ShortId>,
+    pub prefilled: Vec<PrefilledTransaction>,
+}
+
+impl CompactBlock {
+    /// Derives the SipHash-2-4 `(k0, k1)` key for this compact block, per
+    /// BIP152: double-SHA256 of the header serialization followed by the
+    /// 8-byte little-endian nonce, taking the first 16 bytes as two
+    /// little-endian `u64`s.
+    fn short_id_key(&self) -> (u64, u64) {
+        let mut preimage = Vec::new();
+        self.header.consensus_encode(&mut preimage).unwrap();
+        preimage.extend_from_slice(&self.nonce.to_le_bytes());
+        let hash = double_sha256(&preimage);
+        let k0 = u64::from_le_bytes(hash[0..8].try_into().unwrap());
+        let k1 = u64::from_le_bytes(hash[8..16].try_into().unwrap());
+        (k0, k1)
+    }
+
+    /// Computes the short ID a peer announcing this compact block would use
+    /// for `tx`, i.e. the 6 least significant bytes of
+    /// `SipHash-2-4(k0, k1, wtxid)`.
+    pub fn short_id_for(&self, tx: &Transaction) -> ShortId {
+        let (k0, k1) = self.short_id_key();
+
+        let mut wtxid_bytes = Vec::new();
+        tx.wtxid().consensus_encode(&mut wtxid_bytes).unwrap();
+
+        let digest = siphash24(k0, k1, &wtxid_bytes);
+        let mut id = [0u8; 6];
+        id.copy_from_slice(&digest.to_le_bytes()[..6]);
+        id
+    }
+}
+
+impl Serialize for CompactBlock {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let short_ids: Vec<String> = self.short_ids.iter().map(hex::encode).collect();
+
+        // Short IDs computed from the already-known prefilled transactions,
+        // surfaced alongside `short_ids` so a caller reconstructing the block
+        // can cross-check a prefilled transaction against the announced list
+        // without recomputing `short_id_for` itself.
+        let prefilled_short_ids: Vec<String> = self
+            .prefilled
+            .iter()
+            .map(|p| hex::encode(self.short_id_for(&p.tx)))
+            .collect();
+
+        let mut block = serializer.serialize_struct("CompactBlock", 5)?;
+        block.serialize_field("header", &self.header)?;
+        block.serialize_field("nonce", &self.nonce)?;
+        block.serialize_field("short_ids", &short_ids)?;
+        block.serialize_field("prefilled", &self.prefilled)?;
+        block.serialize_field("prefilled_short_ids", &prefilled_short_ids)?;
+        block.end()
+    }
+}
+
+impl Encodable for CompactBlock {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
+        let mut len = 0;
+        len += self.header.consensus_encode(writer)?;
+        len += self.nonce.consensus_encode(writer)?;
+        len += self.short_ids.consensus_encode(writer)?;
+        len += self.prefilled.consensus_encode(writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for CompactBlock {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let header = BlockHeader::consensus_decode_from_finite_reader(reader)?;
+        let nonce = u64::consensus_decode_from_finite_reader(reader)?;
+        let short_ids = Vec::<ShortId>::consensus_decode_from_finite_reader(reader)?;
+        let prefilled = Vec::<PrefilledTransaction>::consensus_decode_from_finite_reader(reader)?;
+        Ok(CompactBlock {
+            header,
+            nonce,
+            short_ids,
+            prefilled,
+        })
+    }
+}
+
+/// SipHash-2-4 (2 compression rounds, 4 finalization rounds) over an
+/// arbitrary-length byte slice, per Aumasson & Bernstein. BIP152 uses this
+/// (and only this) to derive compact-block short IDs, so it's kept local
+/// here rather than exposed as a general-purpose hasher.
+fn siphash24(k0: u64, k1: u64, data: &[u8]) -> u64 {
+    let mut v0 = 0x736f6d6570736575u64 ^ k0;
+    let mut v1 = 0x646f72616e646f6du64 ^ k1;
+    let mut v2 = 0x6c7967656e657261u64 ^ k0;
+    let mut v3 = 0x7465646279746573u64 ^ k1;
+
+    macro_rules! sipround {
+        () => {{
+            v0 = v0.wrapping_add(v1);
+            v1 = v1.rotate_left(13);
+            v1 ^= v0;
+            v0 = v0.rotate_left(32);
+            v2 = v2.wrapping_add(v3);
+            v3 = v3.rotate_left(16);
+            v3 ^= v2;
+            v0 = v0.wrapping_add(v3);
+            v3 = v3.rotate_left(21);
+            v3 ^= v0;
+            v2 = v2.wrapping_add(v1);
+            v1 = v1.rotate_left(17);
+            v1 ^= v2;
+            v2 = v2.rotate_left(32);
+        }};
+    }
+
+    let chunks = data.chunks_exact(8);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let block = u64::from_le_bytes(chunk.try_into().unwrap());
+        v3 ^= block;
+        sipround!();
+        sipround!();
+        v0 ^= block;
+    }
+
+    let mut last_block = [0u8; 8];
+    last_block[..tail.len()].copy_from_slice(tail);
+    last_block[7] = data.len() as u8;
+    let last = u64::from_le_bytes(last_block);
+    v3 ^= last;
+    sipround!();
+    sipround!();
+    v0 ^= last;
+
+    v2 ^= 0xff;
+    sipround!();
+    sipround!();
+    sipround!();
+    sipround!();
+
+    v0 ^ v1 ^ v2 ^ v3
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::siphash24;
+
+    #[test]
+    fn test_siphash24_matches_reference_test_vector() {
+        // From the SipHash reference implementation's test vectors: key
+        // bytes 0x00..0x0f (as little-endian k0/k1), input the single byte
+        // 0x00, expected output 0x74f839c593dc67fd (vectors[1]).
+        let k0 = 0x0706050403020100u64;
+        let k1 = 0x0f0e0d0c0b0a0908u64;
+        let digest = siphash24(k0, k1, &[0x00]);
+        assert_eq!(digest, 0x74f839c593dc67fd);
+    }
+}
@@ -1,85 +1,13 @@
-use std::io::Read;
+use transaction_decoder::{get_arg, run};
 
-fn read_version(transaction_bytes: &mut &[u8]) -> u32 {
-    let mut buffer = [0; 4];
-    transaction_bytes.read(&mut buffer).unwrap();
-
-    u32::from_le_bytes(buffer)
-}
-
-fn read_compact_size(transaction_bytes: &mut &[u8]) -> u64 {
-    let mut compact_size = [0; 1];
-    transaction_bytes.read(&mut compact_size).unwrap();
-
-    let first_byte = compact_size[0];
-    match first_byte {
-        (1..=252) => compact_size[0] as u64,
-        253 => {
-            let mut buffer = [0; 2];
-            transaction_bytes.read(&mut buffer).unwrap();
-            u16::from_le_bytes(buffer) as u64
-        }
-        254 => {
-            let mut buffer = [0; 4];
-            transaction_bytes.read(&mut buffer).unwrap();
-            u32::from_le_bytes(buffer) as u64
-        }
-        255 => {
-            let mut buffer = [0; 8];
-            transaction_bytes.read(&mut buffer).unwrap();
-            u64::from_le_bytes(buffer) as u64
-        }
-        _ => panic!("invalid compact size"),
-    }
-}
 fn main() {
-    let transaction_hex = "010000000242d5c1d6f7308bbe95c0f6e1301dd73a8da77d2155b0773bc297ac47f9cd7380010000006a4730440220771361aae55e84496b9e7b06e0a53dd122a1425f85840af7a52b20fa329816070220221dd92132e82ef9c133cb1a106b64893892a11acf2cfa1adb7698dcdc02f01b0121030077be25dc482e7f4abad60115416881fe4ef98af33c924cd8b20ca4e57e8bd5feffffff75c87cc5f3150eefc1c04c0246e7e0b370e64b17d6226c44b333a6f4ca14b49c000000006b483045022100e0d85fece671d367c8d442a96230954cdda4b9cf95e9edc763616d05d93e944302202330d520408d909575c5f6976cc405b3042673b601f4f2140b2e4d447e671c47012103c43afccd37aae7107f5a43f5b7b223d034e7583b77c8cd1084d86895a7341abffeffffff02ebb10f00000000001976a9144ef88a0b04e3ad6d1888da4be260d6735e0d308488ac508c1e000000000017a91476c0c8f2fc403c5edaea365f6a284317b9cdf7258700000000";
-    let transaction_bytes = hex::decode(transaction_hex).unwrap();
-    let mut bytes_slice = transaction_bytes.as_slice();
-
-    let version = read_version(&mut bytes_slice);
-    let input_length = read_compact_size(&mut bytes_slice);
+    let raw_transaction_hex = get_arg();
 
-    println!("Version: {}", version);
-    println!("Input Length: {}", input_length);
-}
-
-#[cfg(test)]
-mod unit_tests {
-    use super::read_compact_size;
-
-    #[test]
-    fn test_read_compact_size() {
-        let mut bytes = [1_u8].as_slice();
-        let length = read_compact_size(&mut bytes);
-        assert_eq!(length, 1_u64);
-
-        let mut bytes = [253_u8, 0, 1].as_slice();
-        let length = read_compact_size(&mut bytes);
-        assert_eq!(length, 256_u64);
-
-        let mut bytes = [254_u8, 0, 0, 0, 1].as_slice();
-        let length = read_compact_size(&mut bytes);
-        assert_eq!(length, 256_u64.pow(3));
-
-        let mut bytes = [255_u8, 0, 0, 0, 0, 0, 0, 0, 1].as_slice();
-        let length = read_compact_size(&mut bytes);
-        assert_eq!(length, 256_u64.pow(7));
-
-        // https://mempool.space/tx/52539a56b1eb890504b775171923430f0355eb836a57134ba598170a2f8980c1
-        // fd is 253
-        // transaction has 20,000 empty inputs
-        let hex = "fd204e";
-        let decoded = hex::decode(transaction_hex).unwrap();
-        let mut bytes = decoded.as_slice();
-        let length = read_compact_size(&mut bytes);
-        let expected_length = 20_000_u64;
-        assert_eq!(length, expected_length);
-
-        let result = std::panic::catch_unwind(|| {
-            let mut bytes = [0_u8].as_slice();
-            read_compact_size(&mut bytes);
-        });
-        assert!(result.is_err());
+    match run(raw_transaction_hex) {
+        Ok(output) => println!("{}", output),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
     }
 }
@@ -8,12 +8,26 @@ use std::io::{Read, Write};
 #[derive(fmt::Debug)]
 pub enum Error {
     Io(std::io::Error),
+    /// A `CompactSize` was encoded with more bytes than its value requires,
+    /// e.g. a 3-byte `0xFD`-prefixed encoding of a value that fits in a
+    /// single byte. Only returned by the strict decode path.
+    ///
+    /// `CompactSize::consensus_decode_strict` (and therefore this variant)
+    /// is called only from this module's unit tests; the `transaction`
+    /// module is private to the crate, so nothing outside it can reach
+    /// either. Allowed explicitly rather than silenced by giving it a fake
+    /// call site.
+    #[allow(dead_code)]
+    NonMinimalCompactSize,
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Io(e) => write!(f, "IO Error: {}", e),
+            Self::NonMinimalCompactSize => {
+                write!(f, "non-canonical (non-minimal) CompactSize encoding")
+            }
         }
     }
 }
@@ -26,9 +40,14 @@ pub struct Transaction {
     pub inputs: Vec<TxIn>,
     pub outputs: Vec<TxOut>,
     pub lock_time: u32,
+    /// `None` for a legacy transaction; `Some` (one `Witness` per input) for
+    /// a transaction parsed from the BIP144 marker/flag/witness encoding.
+    pub witnesses: Option<Vec<Witness>>,
 }
 
 impl Transaction {
+    /// Hash of the legacy serialization (no marker, flag, or witness data),
+    /// per BIP141 this stays stable across malleation of the witness.
     pub fn txid(&self) -> Txid {
         let mut txid_data = Vec::new();
         self.version.consensus_encode(&mut txid_data).unwrap();
@@ -37,28 +56,87 @@ impl Transaction {
         self.lock_time.consensus_encode(&mut txid_data).unwrap();
         Txid::new(txid_data)
     }
+
+    /// Hash of the full witness serialization (BIP144), including the
+    /// marker/flag and witness stacks when present.
+    pub fn wtxid(&self) -> Txid {
+        let mut wtxid_data = Vec::new();
+        self.consensus_encode(&mut wtxid_data).unwrap();
+        Txid::new(wtxid_data)
+    }
+
+    /// Length of the legacy (marker/flag/witness-stripped) serialization, in bytes.
+    ///
+    /// Computed independently of `total_size` (rather than derived from it by
+    /// subtracting witness bytes), so a transaction with zero inputs — whose
+    /// witness-included serialization happens to start with the same `0x00`
+    /// byte as a legacy zero-input count — can't throw the two counts out of
+    /// sync with each other.
+    fn base_size(&self) -> usize {
+        let mut writer = CountingWriter::default();
+        self.version.consensus_encode(&mut writer).unwrap();
+        self.inputs.consensus_encode(&mut writer).unwrap();
+        self.outputs.consensus_encode(&mut writer).unwrap();
+        self.lock_time.consensus_encode(&mut writer).unwrap();
+        writer.count
+    }
+
+    /// Length of the full serialization (marker/flag/witnesses included, if any), in bytes.
+    fn total_size(&self) -> usize {
+        let mut writer = CountingWriter::default();
+        self.consensus_encode(&mut writer).unwrap();
+        writer.count
+    }
+
+    /// Transaction weight per BIP141: `base_size * 3 + total_size`.
+    pub fn weight(&self) -> usize {
+        self.base_size() * 3 + self.total_size()
+    }
+
+    /// Virtual size per BIP141: `ceil(weight / 4)`.
+    pub fn vsize(&self) -> usize {
+        self.weight().div_ceil(4)
+    }
+}
+
+/// A `Write` sink that only counts bytes, used to size a serialization
+/// without allocating a buffer for it.
+#[derive(Default)]
+struct CountingWriter {
+    count: usize,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.count += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 pub trait Encodable {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error>;
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error>;
 }
 
 impl Encodable for Version {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let len = self.0.consensus_encode(writer)?;
         Ok(len)
     }
 }
 
 impl Encodable for u8 {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let len = writer.write([*self].as_slice()).map_err(Error::Io)?;
         Ok(len)
     }
 }
 
 impl Encodable for u16 {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let bytes = self.to_le_bytes();
         let len = writer.write(bytes.as_slice()).map_err(Error::Io)?;
         Ok(len)
@@ -66,7 +144,7 @@ impl Encodable for u16 {
 }
 
 impl Encodable for u32 {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let bytes = self.to_le_bytes();
         let len = writer.write(bytes.as_slice()).map_err(Error::Io)?;
         Ok(len)
@@ -74,7 +152,7 @@ impl Encodable for u32 {
 }
 
 impl Encodable for u64 {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let bytes = self.to_le_bytes();
         let len = writer.write(bytes.as_slice()).map_err(Error::Io)?;
         Ok(len)
@@ -82,7 +160,7 @@ impl Encodable for u64 {
 }
 
 impl Encodable for Vec<TxIn> {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let mut len = 0;
         len += CompactSize(self.len() as u64).consensus_encode(writer)?;
         for input in self.iter() {
@@ -93,7 +171,7 @@ impl Encodable for Vec<TxIn> {
 }
 
 impl Encodable for TxIn {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let mut len = 0;
         len += self.previous_txid.consensus_encode(writer)?;
         len += self.previous_vout.consensus_encode(writer)?;
@@ -104,14 +182,14 @@ impl Encodable for TxIn {
 }
 
 impl Encodable for Txid {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let buff = self.0.as_slice();
         Ok(writer.write(buff).map_err(Error::Io)?)
     }
 }
 
 impl Encodable for String {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let b = hex::decode(self).expect("should be a valid hex string");
         let compact_size_len = CompactSize(b.len() as u64).consensus_encode(writer)?;
         let b_len = writer.write(&b).map_err(Error::Io)?;
@@ -120,7 +198,7 @@ impl Encodable for String {
 }
 
 impl Encodable for Vec<TxOut> {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let mut len = 0;
         len += CompactSize(self.len() as u64).consensus_encode(writer)?;
         for input in self.iter() {
@@ -131,7 +209,7 @@ impl Encodable for Vec<TxOut> {
 }
 
 impl Encodable for TxOut {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let mut len = 0;
         len += self.amount.consensus_encode(writer)?;
         len += self.script_pubkey.consensus_encode(writer)?;
@@ -140,13 +218,45 @@ impl Encodable for TxOut {
 }
 
 impl Encodable for Amount {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         Ok(self.0.consensus_encode(writer)?)
     }
 }
 
+impl Encodable for Witness {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
+        let mut len = 0;
+        len += CompactSize(self.0.len() as u64).consensus_encode(writer)?;
+        for item in self.0.iter() {
+            len += CompactSize(item.len() as u64).consensus_encode(writer)?;
+            len += writer.write(item).map_err(Error::Io)?;
+        }
+        Ok(len)
+    }
+}
+
+impl Encodable for Transaction {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
+        let mut len = 0;
+        len += self.version.consensus_encode(writer)?;
+        if let Some(witnesses) = &self.witnesses {
+            len += writer.write(&[0x00, 0x01]).map_err(Error::Io)?;
+            len += self.inputs.consensus_encode(writer)?;
+            len += self.outputs.consensus_encode(writer)?;
+            for witness in witnesses {
+                len += witness.consensus_encode(writer)?;
+            }
+        } else {
+            len += self.inputs.consensus_encode(writer)?;
+            len += self.outputs.consensus_encode(writer)?;
+        }
+        len += self.lock_time.consensus_encode(writer)?;
+        Ok(len)
+    }
+}
+
 impl Encodable for CompactSize {
-    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> Result<usize, Error> {
         let val = self.0;
         match val {
             0..=0xFC => {
@@ -177,42 +287,134 @@ impl Serialize for Transaction {
     where
         S: Serializer,
     {
-        let mut tx = serializer.serialize_struct("Transaction", 5)?;
+        let mut tx = serializer.serialize_struct("Transaction", 8)?;
         tx.serialize_field("transaction_id", &self.txid())?;
         tx.serialize_field("version", &self.version)?;
         tx.serialize_field("inputs", &self.inputs)?;
         tx.serialize_field("outputs", &self.outputs)?;
         tx.serialize_field("locktime", &self.lock_time)?;
+        tx.serialize_field("witnesses", &self.witnesses)?;
+        tx.serialize_field("weight", &self.weight())?;
+        tx.serialize_field("vsize", &self.vsize())?;
         tx.end()
     }
 }
 
 impl Decodable for Transaction {
-    fn consensus_decode<R: Read>(reader: &mut R) -> Result<Self, Error> {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let version = Version::consensus_decode_from_finite_reader(reader)?;
+
+        // Peek the input-count byte: a legacy input count is never zero
+        // (an empty input vector is encoded as the BIP144 marker instead),
+        // so `0x00` here means what follows is `flag, inputs, outputs,
+        // witnesses, locktime` rather than `inputs, outputs, locktime`.
+        let first_byte = u8::consensus_decode_from_finite_reader(reader)?;
+        let is_segwit = first_byte == 0;
+
+        let input_count_byte = if is_segwit {
+            let flag = u8::consensus_decode_from_finite_reader(reader)?;
+            if flag != 1 {
+                return Err(Error::Io(IOError::new(
+                    ErrorKind::InvalidData,
+                    "invalid segwit flag",
+                )));
+            }
+            u8::consensus_decode_from_finite_reader(reader)?
+        } else {
+            first_byte
+        };
+
+        let input_count = CompactSize::consensus_decode_with_first_byte(input_count_byte, reader)?.0;
+        let inputs = decode_vec::<TxIn, R>(input_count, reader)?;
+        let outputs = Vec::<TxOut>::consensus_decode_from_finite_reader(reader)?;
+
+        let witnesses = if is_segwit {
+            let mut witnesses =
+                Vec::with_capacity(std::cmp::min(inputs.len(), MAX_VEC_ALLOC_CAPACITY));
+            for _ in 0..inputs.len() {
+                witnesses.push(Witness::consensus_decode_from_finite_reader(reader)?);
+            }
+            Some(witnesses)
+        } else {
+            None
+        };
+
+        let lock_time = u32::consensus_decode_from_finite_reader(reader)?;
+
         Ok(Transaction {
-            version: Version::consensus_decode(reader)?,
-            inputs: Vec::<TxIn>::consensus_decode(reader)?,
-            outputs: Vec::<TxOut>::consensus_decode(reader)?,
-            lock_time: u32::consensus_decode(reader)?,
+            version,
+            inputs,
+            outputs,
+            lock_time,
+            witnesses,
         })
     }
 }
 
+/// Decodes `len` elements of `T`, reading from an already-bounded `reader`.
+/// The up-front allocation is capped at `MAX_VEC_ALLOC_CAPACITY` regardless
+/// of `len`, so a length prefix claiming far more elements than the input
+/// actually contains can't be used to force a huge allocation before the
+/// resulting short read is even noticed.
+pub(crate) fn decode_vec<T: Decodable, R: Read + ?Sized>(
+    len: u64,
+    reader: &mut R,
+) -> Result<Vec<T>, Error> {
+    let mut ret = Vec::with_capacity(std::cmp::min(len, MAX_VEC_ALLOC_CAPACITY as u64) as usize);
+    for _ in 0..len {
+        ret.push(T::consensus_decode_from_finite_reader(reader)?);
+    }
+    Ok(ret)
+}
+
+/// Reads `len` raw bytes from an already-bounded `reader`. Mirrors
+/// `decode_vec`: the up-front allocation is capped at
+/// `MAX_VEC_ALLOC_CAPACITY` regardless of `len`, growing in fixed-size
+/// chunks as bytes are actually read, so a bogus huge length prefix can't
+/// force a huge allocation before the resulting short read is even noticed.
+pub(crate) fn decode_bytes<R: Read + ?Sized>(len: u64, reader: &mut R) -> Result<Vec<u8>, Error> {
+    let mut buffer = Vec::with_capacity(std::cmp::min(len, MAX_VEC_ALLOC_CAPACITY as u64) as usize);
+    let mut chunk = [0u8; MAX_VEC_ALLOC_CAPACITY];
+    let mut remaining = len;
+    while remaining > 0 {
+        let to_read = std::cmp::min(remaining, chunk.len() as u64) as usize;
+        reader
+            .read_exact(&mut chunk[..to_read])
+            .map_err(Error::Io)?;
+        buffer.extend_from_slice(&chunk[..to_read]);
+        remaining -= to_read as u64;
+    }
+    Ok(buffer)
+}
+
+impl Decodable for Vec<Transaction> {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(r: &mut R) -> Result<Self, Error> {
+        let len = CompactSize::consensus_decode_from_finite_reader(r)?.0;
+        decode_vec(len, r)
+    }
+}
+
 #[derive(fmt::Debug)]
 pub struct Txid([u8; 32]);
 
 impl Txid {
     fn new(data: Vec<u8>) -> Txid {
-        let mut hasher = Sha256::new();
-        hasher.update(&data);
-        let hash1 = hasher.finalize();
+        Txid(double_sha256(&data))
+    }
+}
 
-        let mut hasher = Sha256::new();
-        hasher.update(hash1);
-        let hash2 = hasher.finalize();
+/// Double-SHA256, the hash used throughout the consensus layer for txids,
+/// block hashes, and merkle roots.
+pub(crate) fn double_sha256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    let hash1 = hasher.finalize();
 
-        Txid(hash2.into())
-    }
+    let mut hasher = Sha256::new();
+    hasher.update(hash1);
+    let hash2 = hasher.finalize();
+
+    hash2.into()
 }
 
 impl Serialize for Txid {
@@ -224,7 +426,7 @@ impl Serialize for Txid {
 }
 
 impl Decodable for Txid {
-    fn consensus_decode<R: Read>(reader: &mut R) -> Result<Self, Error> {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
         let mut buffer = [0; 32];
         reader.read_exact(&mut buffer).map_err(Error::Io)?;
         Ok(Txid(buffer))
@@ -243,32 +445,27 @@ pub struct TxIn {
 }
 
 impl Decodable for Vec<TxIn> {
-    fn consensus_decode<R: Read>(r: &mut R) -> Result<Self, Error> {
-        let len = CompactSize::consensus_decode(r)?.0;
-        let mut ret = Vec::with_capacity(len as usize);
-        for _ in 0..len {
-            ret.push(TxIn::consensus_decode(r)?);
-        }
-        Ok(ret)
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(r: &mut R) -> Result<Self, Error> {
+        let len = CompactSize::consensus_decode_from_finite_reader(r)?.0;
+        decode_vec(len, r)
     }
 }
 
 impl Decodable for TxIn {
-    fn consensus_decode<R: Read>(r: &mut R) -> Result<Self, Error> {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(r: &mut R) -> Result<Self, Error> {
         Ok(TxIn {
-            previous_txid: Txid::consensus_decode(r)?,
-            previous_vout: u32::consensus_decode(r)?,
-            script_sig: String::consensus_decode(r)?,
-            sequence: u32::consensus_decode(r)?,
+            previous_txid: Txid::consensus_decode_from_finite_reader(r)?,
+            previous_vout: u32::consensus_decode_from_finite_reader(r)?,
+            script_sig: String::consensus_decode_from_finite_reader(r)?,
+            sequence: u32::consensus_decode_from_finite_reader(r)?,
         })
     }
 }
 
 impl Decodable for String {
-    fn consensus_decode<R: Read>(r: &mut R) -> Result<Self, Error> {
-        let script_size = CompactSize::consensus_decode(r)?.0;
-        let mut buffer = vec![0; script_size as usize];
-        r.read_exact(&mut buffer).map_err(Error::Io)?;
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(r: &mut R) -> Result<Self, Error> {
+        let script_size = CompactSize::consensus_decode_from_finite_reader(r)?.0;
+        let buffer = decode_bytes(script_size, r)?;
         Ok(hex::encode(buffer))
     }
 }
@@ -281,34 +478,78 @@ pub struct TxOut {
 }
 
 impl Decodable for Vec<TxOut> {
-    fn consensus_decode<R: Read>(r: &mut R) -> Result<Self, Error> {
-        let len = CompactSize::consensus_decode(r)?.0;
-        let mut ret = Vec::with_capacity(len as usize);
-        for _ in 0..len {
-            ret.push(TxOut::consensus_decode(r)?);
-        }
-        Ok(ret)
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(r: &mut R) -> Result<Self, Error> {
+        let len = CompactSize::consensus_decode_from_finite_reader(r)?.0;
+        decode_vec(len, r)
     }
 }
 
 impl Decodable for TxOut {
-    fn consensus_decode<R: Read>(r: &mut R) -> Result<Self, Error> {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(r: &mut R) -> Result<Self, Error> {
         Ok(TxOut {
-            amount: Amount::from_sat(u64::consensus_decode(r)?),
-            script_pubkey: String::consensus_decode(r)?,
+            amount: Amount::from_sat(u64::consensus_decode_from_finite_reader(r)?),
+            script_pubkey: String::consensus_decode_from_finite_reader(r)?,
         })
     }
 }
 
+/// A single input's witness stack: an ordered list of byte items.
+#[derive(fmt::Debug)]
+pub struct Witness(pub Vec<Vec<u8>>);
+
+impl Serialize for Witness {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let items: Vec<String> = self.0.iter().map(hex::encode).collect();
+        items.serialize(s)
+    }
+}
+
+impl Decodable for Witness {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let count = CompactSize::consensus_decode_from_finite_reader(reader)?.0;
+        let mut items = Vec::with_capacity(std::cmp::min(count, MAX_VEC_ALLOC_CAPACITY as u64) as usize);
+        for _ in 0..count {
+            let len = CompactSize::consensus_decode_from_finite_reader(reader)?.0;
+            items.push(decode_bytes(len, reader)?);
+        }
+        Ok(Witness(items))
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct CompactSize(pub u64);
 
+/// Upper bound on the bytes a single top-level `consensus_decode` call will
+/// read, so a malicious length prefix can't be used to stall on an unbounded
+/// read from the underlying reader.
+const MAX_DECODE_SIZE: u64 = 32 * 1024 * 1024;
+
+/// A small, safe starting capacity for a length-prefixed `Vec` whose length
+/// comes from untrusted input; the vector grows normally past this as
+/// elements are actually read, so a bogus huge count can't force a huge
+/// up-front allocation.
+pub(crate) const MAX_VEC_ALLOC_CAPACITY: usize = 4096;
+
 pub trait Decodable: Sized {
-    fn consensus_decode<R: Read>(reader: &mut R) -> Result<Self, Error>;
+    /// Decodes a value from an unbounded reader. Wraps `reader` in a
+    /// byte-limited reader before delegating to
+    /// `consensus_decode_from_finite_reader`, so that a length prefix
+    /// claiming far more data than actually exists can't be used to drive an
+    /// unbounded allocation before the short read is ever noticed.
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let mut limited = reader.take(MAX_DECODE_SIZE);
+        Self::consensus_decode_from_finite_reader(&mut limited)
+    }
+
+    /// Decodes a value from a reader that is already known to be bounded.
+    /// Implementations that read a length-prefixed collection should read
+    /// from `reader` directly (not re-wrap it) and cap any up-front
+    /// allocation, growing as elements are actually read.
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error>;
 }
 
 impl Decodable for u8 {
-    fn consensus_decode<R: Read>(reader: &mut R) -> Result<Self, Error> {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
         let mut buffer = [0; 1];
         reader.read_exact(&mut buffer).map_err(Error::Io)?;
         Ok(buffer[0]) // endian-ness doesn't matter for 1 byte
@@ -316,7 +557,7 @@ impl Decodable for u8 {
 }
 
 impl Decodable for u16 {
-    fn consensus_decode<R: Read>(reader: &mut R) -> Result<Self, Error> {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
         let mut buffer = [0; 2];
         reader.read_exact(&mut buffer).map_err(Error::Io)?;
         Ok(u16::from_le_bytes(buffer))
@@ -324,7 +565,7 @@ impl Decodable for u16 {
 }
 
 impl Decodable for u32 {
-    fn consensus_decode<R: Read>(reader: &mut R) -> Result<Self, Error> {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
         let mut buffer = [0; 4];
         reader.read_exact(&mut buffer).map_err(Error::Io)?;
         Ok(u32::from_le_bytes(buffer))
@@ -332,7 +573,7 @@ impl Decodable for u32 {
 }
 
 impl Decodable for u64 {
-    fn consensus_decode<R: Read>(reader: &mut R) -> Result<Self, Error> {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
         let mut buffer = [0; 8];
         reader.read_exact(&mut buffer).map_err(Error::Io)?;
         Ok(u64::from_le_bytes(buffer))
@@ -340,35 +581,85 @@ impl Decodable for u64 {
 }
 
 impl Decodable for Version {
-    fn consensus_decode<R: Read>(reader: &mut R) -> Result<Self, Error> {
-        Ok(Version(u32::consensus_decode(reader)?))
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Version(u32::consensus_decode_from_finite_reader(reader)?))
     }
 }
 
-impl Decodable for CompactSize {
-    fn consensus_decode<R: Read>(reader: &mut R) -> Result<Self, Error> {
-        let mut n = u8::consensus_decode(reader)?;
-
+impl CompactSize {
+    /// Decodes the remainder of a `CompactSize` given its already-consumed
+    /// first byte. Split out from `consensus_decode` so callers that must
+    /// peek the first byte (e.g. `Transaction` sniffing the segwit marker)
+    /// can still decode the count without putting the byte back.
+    fn consensus_decode_with_first_byte<R: Read + ?Sized>(n: u8, reader: &mut R) -> Result<Self, Error> {
         match n {
-            (1..=252) => Ok(CompactSize(n as u64)),
+            (0..=252) => Ok(CompactSize(n as u64)),
             253 => {
-                let x = u16::consensus_decode(reader)?;
+                let x = u16::consensus_decode_from_finite_reader(reader)?;
                 Ok(CompactSize(x as u64))
             }
             254 => {
-                let x = u32::consensus_decode(reader)?;
+                let x = u32::consensus_decode_from_finite_reader(reader)?;
                 Ok(CompactSize(x as u64))
             }
             255 => {
-                let x = u64::consensus_decode(reader)?;
+                let x = u64::consensus_decode_from_finite_reader(reader)?;
                 Ok(CompactSize(x))
             }
-            _ => Err(Error::Io(IOError::new(
-                ErrorKind::InvalidInput,
-                "Compact size error: invalid compact size",
-            ))),
         }
     }
+
+    /// Like `consensus_decode_with_first_byte`, but rejects a non-canonical
+    /// (non-minimal) encoding: a prefix byte whose value could have been
+    /// represented in fewer bytes.
+    ///
+    /// Only reachable from `consensus_decode_strict` below, which itself is
+    /// called only from the unit tests (see that function's doc comment) —
+    /// allowed explicitly rather than given a fake call site.
+    #[allow(dead_code)]
+    fn consensus_decode_with_first_byte_strict<R: Read + ?Sized>(
+        n: u8,
+        reader: &mut R,
+    ) -> Result<Self, Error> {
+        let value = Self::consensus_decode_with_first_byte(n, reader)?;
+        let is_minimal = match n {
+            0..=252 => true,
+            253 => value.0 > 252,
+            254 => value.0 > 0xFFFF,
+            255 => value.0 > 0xFFFF_FFFF,
+        };
+        if is_minimal {
+            Ok(value)
+        } else {
+            Err(Error::NonMinimalCompactSize)
+        }
+    }
+
+    /// Decodes a `CompactSize`, rejecting non-canonical (non-minimal)
+    /// encodings. Opt-in: `Decodable::consensus_decode`/
+    /// `consensus_decode_from_finite_reader` stay lenient, so existing
+    /// callers that don't need the stricter check are unaffected.
+    ///
+    /// Nothing in this crate's CLI path calls this yet — `Transaction`,
+    /// `Block`, and `CompactBlock` decode every `CompactSize` leniently, and
+    /// switching that would mean threading a strictness flag through every
+    /// `Decodable` impl in the series, not just this one. Only the unit
+    /// tests call it, and the `transaction` module is private to the crate,
+    /// so it is not reachable from outside the crate either; the dead-code
+    /// warning is allowed explicitly rather than worked around with an
+    /// unused call site.
+    #[allow(dead_code)]
+    pub fn consensus_decode_strict<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let n = u8::consensus_decode_from_finite_reader(reader)?;
+        Self::consensus_decode_with_first_byte_strict(n, reader)
+    }
+}
+
+impl Decodable for CompactSize {
+    fn consensus_decode_from_finite_reader<R: Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let n = u8::consensus_decode_from_finite_reader(reader)?;
+        Self::consensus_decode_with_first_byte(n, reader)
+    }
 }
 
 #[derive(fmt::Debug)]
@@ -394,3 +685,152 @@ fn as_btc<T: BitcoinValue, S: Serializer>(t: &T, s: S) -> Result<S::Ok, S::Error
     let btc = t.to_btc();
     s.serialize_f64(btc)
 }
+
+#[cfg(test)]
+mod unit_tests {
+    use super::{CompactSize, Decodable, Encodable, Error, Transaction, Version};
+
+    #[test]
+    fn test_wtxid_differs_from_txid_on_segwit_transaction() {
+        // A well-formed one-input, one-output P2WPKH segwit transaction
+        // (marker/flag + one witness stack). `txid` hashes only the legacy
+        // serialization while `wtxid` hashes the full witness serialization,
+        // so the two must disagree, and each must match the double-SHA256
+        // of the respective serialization computed independently.
+        let hex = "01000000000101aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa0000000000ffffffff01a086010000000000160014bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb0247cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc21dddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddd00000000";
+        let decoded = hex::decode(hex).unwrap();
+        let mut bytes = decoded.as_slice();
+        let tx = Transaction::consensus_decode(&mut bytes).unwrap();
+
+        let expected_txid =
+            hex::decode("8b47d4adad5a2041f264f5c7345dd40dc0c0bd27198ec34f0d9460e4dc155f31")
+                .unwrap();
+        let expected_wtxid =
+            hex::decode("0d1395c546185dabb63302ac9b29b0e35ec728fd33deb68722e4e2dcfe7f22f4")
+                .unwrap();
+
+        assert_eq!(tx.txid().0.as_slice(), expected_txid.as_slice());
+        assert_eq!(tx.wtxid().0.as_slice(), expected_wtxid.as_slice());
+        assert_ne!(tx.txid().0, tx.wtxid().0);
+    }
+
+    #[test]
+    fn test_weight_and_vsize_zero_input_segwit() {
+        // A degenerate but well-formed segwit transaction with no inputs:
+        // its witness-included serialization happens to start with the same
+        // `0x00` byte that a legacy zero-input count would, so this exercises
+        // the base/total size split staying correct regardless.
+        let tx = Transaction {
+            version: Version(1),
+            inputs: vec![],
+            outputs: vec![],
+            lock_time: 0,
+            witnesses: Some(vec![]),
+        };
+
+        // version(4) + input count(1) + output count(1) + locktime(4)
+        assert_eq!(tx.base_size(), 10);
+        // base_size + marker(1) + flag(1)
+        assert_eq!(tx.total_size(), 12);
+        assert_eq!(tx.weight(), 10 * 3 + 12);
+        assert_eq!(tx.vsize(), (tx.weight() + 3) / 4);
+    }
+
+    #[test]
+    fn test_weight_legacy_matches_base_and_total() {
+        let tx = Transaction {
+            version: Version(1),
+            inputs: vec![],
+            outputs: vec![],
+            lock_time: 0,
+            witnesses: None,
+        };
+
+        assert_eq!(tx.base_size(), tx.total_size());
+        assert_eq!(tx.weight(), tx.base_size() * 4);
+    }
+
+    #[test]
+    fn test_compact_size_decode() {
+        let mut bytes = [1_u8].as_slice();
+        let length = CompactSize::consensus_decode(&mut bytes).unwrap().0;
+        assert_eq!(length, 1_u64);
+
+        let mut bytes = [253_u8, 0, 1].as_slice();
+        let length = CompactSize::consensus_decode(&mut bytes).unwrap().0;
+        assert_eq!(length, 256_u64);
+
+        let mut bytes = [254_u8, 0, 0, 0, 1].as_slice();
+        let length = CompactSize::consensus_decode(&mut bytes).unwrap().0;
+        assert_eq!(length, 256_u64.pow(3));
+
+        let mut bytes = [255_u8, 0, 0, 0, 0, 0, 0, 0, 1].as_slice();
+        let length = CompactSize::consensus_decode(&mut bytes).unwrap().0;
+        assert_eq!(length, 256_u64.pow(7));
+
+        // https://mempool.space/tx/52539a56b1eb890504b775171923430f0355eb836a57134ba598170a2f8980c1
+        // fd is 253
+        // transaction has 20,000 empty inputs
+        let hex = "fd204e";
+        let decoded = hex::decode(hex).unwrap();
+        let mut bytes = decoded.as_slice();
+        let length = CompactSize::consensus_decode(&mut bytes).unwrap().0;
+        let expected_length = 20_000_u64;
+        assert_eq!(length, expected_length);
+    }
+
+    #[test]
+    fn test_vec_decode_rejects_huge_claimed_length_without_oom() {
+        use super::TxIn;
+
+        // Claims 2^38 (about 275 billion) inputs (0xFF prefix) but supplies
+        // none: the up-front allocation must stay small and the read must
+        // fail on the first missing `TxIn`, rather than attempting to
+        // allocate for 2^38 elements.
+        let hex = "ff0000000040000000";
+        let decoded = hex::decode(hex).unwrap();
+        let mut bytes = decoded.as_slice();
+        let result = Vec::<TxIn>::consensus_decode(&mut bytes);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_compact_size_strict_round_trips_minimal_encodings() {
+        let minimal_encodings: &[&[u8]] = &[
+            &[0],
+            &[252],
+            &[253, 253, 0],
+            &[253, 255, 255],
+            &[254, 0, 0, 1, 0],
+            &[255, 0, 0, 0, 0, 1, 0, 0, 0],
+        ];
+
+        for bytes in minimal_encodings {
+            let mut reader = *bytes;
+            let value = CompactSize::consensus_decode_strict(&mut reader).unwrap();
+
+            let mut encoded = Vec::new();
+            value.consensus_encode(&mut encoded).unwrap();
+            assert_eq!(&encoded, bytes);
+        }
+    }
+
+    #[test]
+    fn test_compact_size_strict_rejects_non_minimal_encoding() {
+        // 0xFD-prefixed 3-byte encoding of 5, which fits in a single byte;
+        // the lenient decoder accepts it, the strict one must not.
+        let bytes = [253_u8, 5, 0];
+
+        let mut reader = bytes.as_slice();
+        assert_eq!(
+            CompactSize::consensus_decode(&mut reader).unwrap().0,
+            5_u64
+        );
+
+        let mut reader = bytes.as_slice();
+        assert!(matches!(
+            CompactSize::consensus_decode_strict(&mut reader),
+            Err(Error::NonMinimalCompactSize)
+        ));
+    }
+}